@@ -1,11 +1,17 @@
 use anyhow::{Context, Result};
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 use crossbeam_channel::{select, Receiver};
+use globset::{Glob, GlobSet, GlobSetBuilder};
 use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use log::{debug, info};
 use nix::errno::Errno;
-use notify_debouncer_mini::notify::RecursiveMode;
-use notify_debouncer_mini::{new_debouncer, DebounceEventResult};
+use notify_debouncer_mini::notify::{
+    Config as NotifyConfig, PollWatcher, RecommendedWatcher, RecursiveMode,
+};
+use notify_debouncer_mini::{
+    new_debouncer, new_debouncer_opt, DebounceEventResult, DebouncedEvent, DebouncedEventKind,
+    Debouncer,
+};
 use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::fmt::Debug;
@@ -19,38 +25,100 @@ use thiserror::Error;
 #[cfg(unix)]
 use {
     nix::sys::signal::{self, Signal},
-    nix::sys::wait::{Id, WaitPidFlag},
+    nix::sys::wait::{Id, WaitPidFlag, WaitStatus},
     nix::unistd::Pid,
     std::os::unix::process::CommandExt,
 };
 
+/// Look up the process group of `child`, or `None` if it no longer exists
 #[cfg(unix)]
-fn kill(child: &mut Child, sig: &str) -> Result<()> {
-    let sig = sig.parse::<Signal>()?;
-    let pgid = match nix::unistd::getpgid(Some(Pid::from_raw(child.id() as i32))) {
-        Ok(pid) => pid,
+fn pgid_of(child: &Child) -> Result<Option<Pid>> {
+    match nix::unistd::getpgid(Some(Pid::from_raw(child.id() as i32))) {
+        Ok(pid) => Ok(Some(pid)),
         // Pid does not exist
-        Err(Errno::ESRCH) => return Ok(()),
-        Err(e) => Err(e)?,
+        Err(Errno::ESRCH) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Send `sig` to the child's process group, then wait up to `timeout` for it to exit,
+/// escalating to `SIGKILL` if it hasn't
+#[cfg(unix)]
+fn kill(child: &mut Child, sig: &str, timeout: Duration) -> Result<()> {
+    let sig = sig.parse::<Signal>()?;
+    let pgid = match pgid_of(child)? {
+        Some(pgid) => pgid,
+        None => return Ok(()),
     };
 
     signal::killpg(pgid, sig)?;
 
+    if wait_for_exit(pgid, timeout)? {
+        return Ok(());
+    }
+
+    debug!(
+        "Process group {} did not exit within {:?} of the stop signal, escalating to SIGKILL",
+        pgid, timeout
+    );
+    signal::killpg(pgid, Signal::SIGKILL)?;
+
     // HACK: we use a custom nix crate to have waitid available on macos.
     // Not sure why they feature flagged macos, it definitely has a posix compliant waitid implementation.
-    nix::sys::wait::waitid(Id::PGid(pgid), WaitPidFlag::all())
+    nix::sys::wait::waitid(Id::PGid(pgid), WaitPidFlag::WEXITED)
         .map(|_| ())
         .map_err(Into::into)
 }
 
+/// Poll the process group with `WNOHANG` until it exits or `timeout` elapses
+#[cfg(unix)]
+fn wait_for_exit(pgid: Pid, timeout: Duration) -> Result<bool> {
+    let deadline = std::time::Instant::now() + timeout;
+    loop {
+        match nix::sys::wait::waitid(Id::PGid(pgid), WaitPidFlag::WEXITED | WaitPidFlag::WNOHANG) {
+            Ok(WaitStatus::StillAlive) => {}
+            Ok(_) => return Ok(true),
+            Err(Errno::ESRCH) => return Ok(true),
+            Err(e) => return Err(e.into()),
+        }
+
+        if std::time::Instant::now() >= deadline {
+            return Ok(false);
+        }
+
+        thread::sleep(Duration::from_millis(25));
+    }
+}
+
+/// Forward a signal to the process group without waiting for it to exit
+#[cfg(unix)]
+fn signal_only(child: &Child, sig: &str) -> Result<()> {
+    let sig = sig.parse::<Signal>()?;
+    let pgid = match pgid_of(child)? {
+        Some(pgid) => pgid,
+        None => return Ok(()),
+    };
+
+    signal::killpg(pgid, sig).map_err(Into::into)
+}
+
+#[cfg(windows)]
+fn signal_only(_child: &Child, _sig: &str) -> Result<()> {
+    Err(anyhow::anyhow!(
+        "sending a signal without killing the process is not supported on Windows"
+    ))
+}
+
 fn spawn(
     program: impl AsRef<str>,
     args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+    events: &[DebouncedEvent],
 ) -> Result<Child> {
     let mut cmd = Command::new(program.as_ref());
     cmd.args(args.into_iter());
 
     configure_command(&mut cmd);
+    set_event_env_vars(&mut cmd, events);
 
     cmd.spawn().context(format!(
         "failed to spawn the provided utility: {}",
@@ -58,6 +126,46 @@ fn spawn(
     ))
 }
 
+/// Expose the paths that triggered this run to the utility via environment variables
+fn set_event_env_vars(cmd: &mut Command, events: &[DebouncedEvent]) {
+    if events.is_empty() {
+        return;
+    }
+
+    let paths: Vec<String> = events
+        .iter()
+        .map(|e| e.path.to_string_lossy().into_owned())
+        .collect();
+    cmd.env("IOWATCH_EVENTS", paths.join("\n"));
+
+    let paths_for_kind = |kind: DebouncedEventKind| -> String {
+        events
+            .iter()
+            .filter(|e| e.kind == kind)
+            .map(|e| e.path.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+    cmd.env("IOWATCH_EVENTS_ANY", paths_for_kind(DebouncedEventKind::Any));
+    cmd.env(
+        "IOWATCH_EVENTS_ANY_CONTINUOUS",
+        paths_for_kind(DebouncedEventKind::AnyContinuous),
+    );
+}
+
+/// Substitute any `{}` argument with the paths that triggered the run, like `find -exec {} +`
+fn substitute_placeholders(args: &[String], paths: &[String]) -> Vec<String> {
+    let mut result = Vec::with_capacity(args.len());
+    for arg in args {
+        if arg == "{}" && !paths.is_empty() {
+            result.extend(paths.iter().cloned());
+        } else {
+            result.push(arg.clone());
+        }
+    }
+    result
+}
+
 #[cfg(unix)]
 fn configure_command(cmd: &mut Command) {
     cmd.process_group(0);
@@ -69,19 +177,35 @@ fn configure_command(_cmd: &mut Command) {
 }
 
 #[cfg(windows)]
-fn kill(child: &mut Child, _sig: &str) -> Result<()> {
+fn kill(child: &mut Child, _sig: &str, _timeout: Duration) -> Result<()> {
     child
         .kill()
         .with_context(|| format!("failed to kill child process"))?;
     child.wait().map(|_| ()).map_err(Into::into)
 }
 
+/// Default `--stop-timeout` in ms, also used as the default for `IoWatch::with_handler`
+const DEFAULT_STOP_TIMEOUT_MS: u64 = 5000;
+
 #[derive(Debug, Error)]
 pub enum IoWatchError {
     #[error("no files or directories to watch")]
     NoFilesToWatch,
 }
 
+/// How to handle a debounced event while the utility is still running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OnBusyUpdate {
+    /// Kill the running utility and respawn it (default)
+    Restart,
+    /// Ignore the event, let the current run finish undisturbed
+    DoNothing,
+    /// Remember the event and perform exactly one coalesced run once the utility exits
+    Queue,
+    /// Forward a signal to the running utility without killing it
+    Signal,
+}
+
 #[derive(Debug, Parser)]
 #[command(name = "iowatch")]
 #[command(about = "Cross platform way to run arbitrary commands when files change")]
@@ -111,59 +235,404 @@ pub struct Cli {
     /// The time delay in ms to apply before running the utility
     #[arg(short = 'd', default_value = "100")]
     delay: u64,
-    /// The kill signal to use, defaults to SIGTERM
-    #[arg(short = 'k', default_value = "SIGTERM")]
+    /// The signal sent to the process group to request a graceful stop, defaults to SIGTERM
+    #[arg(short = 'k', long = "stop-signal", default_value = "SIGTERM")]
     kill_signal: String,
+    /// How long in ms to wait for the process group to exit after the stop signal before escalating to SIGKILL
+    #[arg(long = "stop-timeout", default_value_t = DEFAULT_STOP_TIMEOUT_MS)]
+    stop_timeout: u64,
+    /// What to do when an event arrives while the utility is still running
+    #[arg(short = 'o', long = "on-busy-update", default_value = "restart")]
+    on_busy_update: OnBusyUpdate,
+    /// The signal to forward to the running utility when `--on-busy-update signal` is used
+    #[arg(long = "busy-signal", default_value = "SIGHUP")]
+    busy_signal: String,
+    /// Use a polling watcher with the given interval in ms instead of the native OS watcher,
+    /// useful on network/virtual filesystems (NFS, SMB, Docker bind mounts) where native events
+    /// are unreliable
+    #[arg(long = "poll")]
+    poll: Option<u64>,
+    /// Only trigger when a changed path matches at least one of these globs (repeatable).
+    /// Ignores still win over filters.
+    #[arg(long = "filter")]
+    filter: Vec<String>,
+    /// Never trigger for changed paths matching this glob, independent of .gitignore/.ignore (repeatable)
+    #[arg(long = "ignore")]
+    ignore: Vec<String>,
     /// The utility to run when files change
     utility: Vec<String>,
 }
 
-pub struct IoWatch {
-    exit_after: bool,
-    postpone: bool,
-    recursive_mode: RecursiveMode,
-    files: Vec<String>,
-    timeout: Duration,
+/// The debouncer backend, either the OS-native watcher or a polling fallback for
+/// filesystems that don't deliver native events (network/virtual mounts)
+enum AnyDebouncer {
+    Native(Debouncer<RecommendedWatcher>),
+    Poll(Debouncer<PollWatcher>),
+}
+
+impl AnyDebouncer {
+    fn watch(&mut self, path: &Path, mode: RecursiveMode) -> notify_debouncer_mini::notify::Result<()> {
+        match self {
+            AnyDebouncer::Native(d) => d.watcher().watch(path, mode),
+            AnyDebouncer::Poll(d) => d.watcher().watch(path, mode),
+        }
+    }
+}
+
+impl Debug for AnyDebouncer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AnyDebouncer::Native(_) => write!(f, "Native"),
+            AnyDebouncer::Poll(_) => write!(f, "Poll"),
+        }
+    }
+}
+
+/// Tools handed to an [`ActionHandler`] so it doesn't have to reimplement process
+/// management (spawning, graceful stop, signalling, terminal handling)
+pub struct ActionContext {
+    stop_timeout: Duration,
+}
+
+impl ActionContext {
+    fn new(stop_timeout: Duration) -> Self {
+        ActionContext { stop_timeout }
+    }
+
+    /// Spawn `program` with `args` in its own process group, exposing `events` to it via
+    /// the `IOWATCH_EVENTS*` environment variables
+    pub fn spawn(
+        &self,
+        program: impl AsRef<str>,
+        args: impl IntoIterator<Item = impl AsRef<OsStr>>,
+        events: &[DebouncedEvent],
+    ) -> Result<Child> {
+        spawn(program, args, events)
+    }
+
+    /// Send `sig` to `child`'s process group, escalating to `SIGKILL` after the configured
+    /// `--stop-timeout` if it doesn't exit
+    pub fn kill(&self, child: &mut Child, sig: &str) -> Result<()> {
+        kill(child, sig, self.stop_timeout)
+    }
+
+    /// Forward `sig` to `child`'s process group without waiting for it to exit
+    pub fn signal(&self, child: &Child, sig: &str) -> Result<()> {
+        signal_only(child, sig)
+    }
+
+    /// Clear the terminal screen
+    pub fn clear_screen(&self) -> Result<()> {
+        Command::new("clear")
+            .status()
+            .or_else(|_| Command::new("cmd").args(&["/c", "cls"]).status())?;
+        Ok(())
+    }
+}
+
+/// Turns qualifying debounced events into an action. [`CommandHandler`] is the default,
+/// spawning the configured utility; embedders can implement their own to trigger
+/// in-process work instead of a subprocess
+pub trait ActionHandler {
+    /// Called for every qualifying event, or with an empty slice on the initial/timeout run
+    fn on_action(&mut self, events: &[DebouncedEvent], ctx: &mut ActionContext) -> Result<()>;
+
+    /// Called once when Ctrl+C is received, right before `IoWatch::run` returns, to let the
+    /// handler clean up. Not called when `run` returns because of `exit_after` or an error
+    fn on_stop(&mut self, _ctx: &mut ActionContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called whenever 100ms pass without an event, timeout or Ctrl+C being handled, so a
+    /// handler can notice state changes that aren't themselves events (e.g. a queued run
+    /// becoming ready)
+    fn on_tick(&mut self, _ctx: &mut ActionContext) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The default [`ActionHandler`]: spawns the configured utility, honouring `--on-busy-update`,
+/// `--stop-signal`/`--stop-timeout`, `-d`/`-c`, and the `{}`/`IOWATCH_EVENTS*` event plumbing
+#[derive(Debug)]
+pub struct CommandHandler {
     delay: u64,
     clear_term: bool,
     kill_sig: String,
+    on_busy_update: OnBusyUpdate,
+    busy_signal: String,
     utility_cmd: Vec<String>,
     utility_process: Option<Child>,
     first_run: bool,
+    /// Set when an event arrives while the utility is busy and `on_busy_update` is `Queue`
+    queued_run: bool,
+    /// Events accumulated while busy, to be coalesced into the next queued run
+    queued_events: Vec<DebouncedEvent>,
+}
+
+impl CommandHandler {
+    fn from_cli(cli: &Cli, utility_cmd: Vec<String>) -> Self {
+        CommandHandler {
+            delay: cli.delay,
+            clear_term: cli.clear_term,
+            kill_sig: cli.kill_signal.clone(),
+            on_busy_update: cli.on_busy_update,
+            busy_signal: cli.busy_signal.clone(),
+            utility_cmd,
+            utility_process: None,
+            first_run: true,
+            queued_run: false,
+            queued_events: Vec::new(),
+        }
+    }
+
+    /// Whether the utility process is still alive
+    fn is_busy(&mut self) -> bool {
+        match self.utility_process {
+            Some(ref mut child) => matches!(child.try_wait(), Ok(None)),
+            None => false,
+        }
+    }
+
+    /// Stop the utility if still running, escalating to SIGKILL if it ignores the stop signal
+    fn kill_utility(&mut self, ctx: &mut ActionContext) -> Result<()> {
+        match self.utility_process {
+            Some(ref mut child) => ctx.kill(child, &self.kill_sig),
+            None => Ok(()),
+        }
+    }
+
+    /// Forward `busy_signal` to the running utility without killing it
+    fn signal_utility(&mut self, ctx: &mut ActionContext) -> Result<()> {
+        match self.utility_process {
+            Some(ref mut child) => ctx.signal(child, &self.busy_signal),
+            None => Ok(()),
+        }
+    }
+
+    /// Wait for a delay in ms
+    fn wait_delay(&self) -> Result<()> {
+        thread::sleep(Duration::from_millis(self.delay));
+        Ok(())
+    }
+
+    /// Run the configured utility, passing along the events that triggered this run
+    fn run_utility(&mut self, events: &[DebouncedEvent], ctx: &mut ActionContext) -> Result<()> {
+        if self.utility_process.is_some() {
+            self.kill_utility(ctx)?;
+        }
+
+        if self.clear_term {
+            ctx.clear_screen().context("Failed to clear terminal screen")?;
+        }
+
+        // apply delay only on subsequent runs
+        if !self.first_run && self.delay > 0 {
+            self.wait_delay()?;
+        }
+
+        let paths: Vec<String> = events
+            .iter()
+            .map(|e| e.path.to_string_lossy().into_owned())
+            .collect();
+        let args = substitute_placeholders(&self.utility_cmd[1..], &paths);
+
+        self.utility_process = Some(ctx.spawn(&self.utility_cmd[0], &args, events)?);
+
+        self.first_run = false;
+
+        Ok(())
+    }
+
+    /// If a run was queued while the utility was busy and it has since exited, perform it now
+    fn run_queued_if_ready(&mut self, ctx: &mut ActionContext) -> Result<()> {
+        if self.queued_run && !self.is_busy() {
+            self.queued_run = false;
+            let events = std::mem::take(&mut self.queued_events);
+            self.run_utility(&events, ctx)?;
+        }
+        Ok(())
+    }
+
+    /// Get the sytem's shell command string
+    fn get_shell_cmd() -> Vec<String> {
+        if cfg!(windows) {
+            vec!["cmd".to_string(), "/c".to_string()]
+        } else {
+            // Assume GNU
+            let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
+            vec![shell, "-c".to_string()]
+        }
+    }
 }
 
-impl IoWatch {
-    /// Run the application
+impl ActionHandler for CommandHandler {
+    /// Dispatch a qualifying event according to the configured `on_busy_update` mode
+    fn on_action(&mut self, events: &[DebouncedEvent], ctx: &mut ActionContext) -> Result<()> {
+        if !self.is_busy() {
+            // a fresh run also covers anything queued while the previous run was busy
+            self.queued_run = false;
+            let mut all_events = std::mem::take(&mut self.queued_events);
+            all_events.extend(events.iter().cloned());
+            return self.run_utility(&all_events, ctx);
+        }
+
+        match self.on_busy_update {
+            OnBusyUpdate::Restart => self.run_utility(events, ctx),
+            OnBusyUpdate::DoNothing => {
+                debug!("Utility is busy, ignoring event as on-busy-update is do-nothing");
+                Ok(())
+            }
+            OnBusyUpdate::Queue => {
+                debug!("Utility is busy, queueing a coalesced run");
+                self.queued_run = true;
+                self.queued_events.extend(events.iter().cloned());
+                Ok(())
+            }
+            OnBusyUpdate::Signal => {
+                debug!("Utility is busy, forwarding busy-signal {}", self.busy_signal);
+                self.signal_utility(ctx)
+            }
+        }
+    }
+
+    fn on_stop(&mut self, ctx: &mut ActionContext) -> Result<()> {
+        self.kill_utility(ctx)
+    }
+
+    fn on_tick(&mut self, ctx: &mut ActionContext) -> Result<()> {
+        self.run_queued_if_ready(ctx)
+    }
+}
+
+pub struct IoWatch<H: ActionHandler = CommandHandler> {
+    exit_after: bool,
+    postpone: bool,
+    recursive_mode: RecursiveMode,
+    files: Vec<String>,
+    timeout: Duration,
+    stop_timeout: Duration,
+    poll_interval: Option<Duration>,
+    filter_globs: Vec<String>,
+    ignore_globs: Vec<String>,
+    handler: H,
+}
+
+impl<H: ActionHandler> IoWatch<H> {
+    /// Build an `IoWatch` driving a custom [`ActionHandler`] instead of the default
+    /// [`CommandHandler`], for embedding iowatch's watch/debounce/ignore machinery in another
+    /// program. Tune the result further with the builder methods below.
+    pub fn with_handler(files: Vec<String>, recursive: bool, handler: H) -> Result<Self> {
+        if files.is_empty() {
+            Err(IoWatchError::NoFilesToWatch)?
+        }
+
+        Ok(IoWatch {
+            exit_after: false,
+            postpone: false,
+            recursive_mode: if recursive {
+                RecursiveMode::Recursive
+            } else {
+                RecursiveMode::NonRecursive
+            },
+            files,
+            timeout: Duration::from_secs(u64::MAX),
+            stop_timeout: Duration::from_millis(DEFAULT_STOP_TIMEOUT_MS),
+            poll_interval: None,
+            filter_globs: Vec::new(),
+            ignore_globs: Vec::new(),
+            handler,
+        })
+    }
+
+    /// Postpone the first action until a file is modified
+    pub fn postpone(mut self, postpone: bool) -> Self {
+        self.postpone = postpone;
+        self
+    }
+
+    /// Exit after the first action completes
+    pub fn exit_after(mut self, exit_after: bool) -> Self {
+        self.exit_after = exit_after;
+        self
+    }
+
+    /// Trigger an action if no action has been triggered for `timeout`; ignored events don't
+    /// delay it
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// How long `ActionContext::kill` waits for the stop signal to take effect before escalating
+    pub fn stop_timeout(mut self, stop_timeout: Duration) -> Self {
+        self.stop_timeout = stop_timeout;
+        self
+    }
+
+    /// Use a polling watcher at the given interval instead of the native OS watcher
+    pub fn poll_interval(mut self, poll_interval: Option<Duration>) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Only fire for paths matching at least one of these globs
+    pub fn filter_globs(mut self, filter_globs: Vec<String>) -> Self {
+        self.filter_globs = filter_globs;
+        self
+    }
+
+    /// Never fire for paths matching any of these globs, independent of .gitignore/.ignore
+    pub fn ignore_globs(mut self, ignore_globs: Vec<String>) -> Self {
+        self.ignore_globs = ignore_globs;
+        self
+    }
+
+    /// Run the application, dispatching qualifying events to the configured [`ActionHandler`]
     pub fn run(mut self) -> Result<()> {
-        debug!("Starting IoWatch with utility: {:?}", self.utility_cmd);
+        debug!("Starting IoWatch");
         let (tx, rx) = crossbeam_channel::unbounded();
-        let mut debouncer = new_debouncer(Duration::from_millis(25), None, tx)?;
-        let watcher = debouncer.watcher();
+        let mut debouncer = self.make_debouncer(tx)?;
+        debug!("Using debouncer backend: {:?}", debouncer);
 
         for f in &self.files {
-            watcher
+            debouncer
                 .watch(f.as_ref(), self.recursive_mode)
                 .with_context(|| format!("Failed to watch {}", f))?;
         }
 
         let ignore_matcher = self.get_ignore_matcher()?;
+        let filter_globs = Self::build_globset(&self.filter_globs)?;
+        let ignore_globs = Self::build_globset(&self.ignore_globs)?;
 
         let ctrlc_rx = self.ctrlc_events()?;
 
         debug!("exit after: {}", self.exit_after);
 
         if !self.postpone {
-            debug!("Running utility immediately as postpone is false");
-            self.run_utility()?;
+            debug!("Running action immediately as postpone is false");
+            self.dispatch_action(&[])?;
             if self.exit_after {
                 debug!("Exiting after first run as requested");
                 return Ok(());
             }
         }
 
+        // tracks when the idle timeout should next fire (`None` when it lies beyond what
+        // `Instant` can hold, i.e. never); the remaining time is recomputed from it on every
+        // pass and the housekeeping tick leaves it untouched, so the 100ms tick racing it in
+        // `select!` can't starve it
+        let mut timeout_deadline = std::time::Instant::now().checked_add(self.timeout);
+
         loop {
-            self.pump_events(rx.clone(), ctrlc_rx.clone(), &ignore_matcher)?;
-            if self.exit_after {
+            let handled = self.pump_events(
+                rx.clone(),
+                ctrlc_rx.clone(),
+                &ignore_matcher,
+                &filter_globs,
+                &ignore_globs,
+                &mut timeout_deadline,
+            )?;
+            if self.exit_after && handled {
                 break;
             }
         }
@@ -171,22 +640,37 @@ impl IoWatch {
         Ok(())
     }
 
+    /// Handles one select! arm; returns `false` when only the housekeeping tick fired
     fn pump_events(
         &mut self,
         rx: Receiver<DebounceEventResult>,
         ctrlc_rx: Receiver<()>,
         ignore_matcher: &Gitignore,
-    ) -> Result<()> {
+        filter_globs: &GlobSet,
+        ignore_globs: &GlobSet,
+        timeout_deadline: &mut Option<std::time::Instant>,
+    ) -> Result<bool> {
+        let remaining_timeout = match *timeout_deadline {
+            Some(deadline) => deadline.saturating_duration_since(std::time::Instant::now()),
+            None => self.timeout,
+        };
+        let mut handled = true;
+
         select! {
             // handle filesystem events
             recv(rx) -> res => {
                 match res {
                     Ok(inner) => match inner {
                         Ok(events) => {
+                            // ignores (gitignore or --ignore) always win over --filter
                             let ignore = events.iter()
-                                .any(|e| ignore_matcher.matched_path_or_any_parents(&e.path, e.path.is_dir()).is_ignore());
-                            if !ignore {
-                                self.run_utility()?;
+                                .any(|e| ignore_matcher.matched_path_or_any_parents(&e.path, e.path.is_dir()).is_ignore()
+                                    || ignore_globs.is_match(&e.path));
+                            let allowed = filter_globs.is_empty()
+                                || events.iter().any(|e| filter_globs.is_match(&e.path));
+                            if !ignore && allowed {
+                                self.dispatch_action(&events)?;
+                                *timeout_deadline = std::time::Instant::now().checked_add(self.timeout);
                             }
                         },
                         Err(errors) =>  errors.iter().for_each(|e| eprintln!("Error {:?}",e)),
@@ -195,19 +679,40 @@ impl IoWatch {
                 }
             },
             // handle timeout case
-            recv(crossbeam::channel::after(self.timeout)) -> _ => {
-                debug!("Timeout reached, running utility");
-                self.run_utility()?;
+            recv(crossbeam::channel::after(remaining_timeout)) -> _ => {
+                debug!("Timeout reached, running action");
+                self.dispatch_action(&[])?;
+                *timeout_deadline = std::time::Instant::now().checked_add(self.timeout);
+            },
+            // poll for handler-internal state changes (e.g. a queued run becoming ready)
+            recv(crossbeam::channel::tick(Duration::from_millis(100))) -> _ => {
+                self.dispatch_tick()?;
+                handled = false;
             },
             // handle ctrl+c
             recv(ctrlc_rx) -> _ => {
                 info!("Ctrl+C received, exiting...");
-                self.kill_utility()?;
+                self.dispatch_stop()?;
                 self.exit_after = true;
             }
         }
 
-        Ok(())
+        Ok(handled)
+    }
+
+    fn dispatch_action(&mut self, events: &[DebouncedEvent]) -> Result<()> {
+        let mut ctx = ActionContext::new(self.stop_timeout);
+        self.handler.on_action(events, &mut ctx)
+    }
+
+    fn dispatch_tick(&mut self) -> Result<()> {
+        let mut ctx = ActionContext::new(self.stop_timeout);
+        self.handler.on_tick(&mut ctx)
+    }
+
+    fn dispatch_stop(&mut self) -> Result<()> {
+        let mut ctx = ActionContext::new(self.stop_timeout);
+        self.handler.on_stop(&mut ctx)
     }
 
     /// Setup a handler and channel receiver for ctrl+c notifications
@@ -220,6 +725,32 @@ impl IoWatch {
         Ok(rx)
     }
 
+    /// Builds the debouncer, using the polling watcher when `--poll` was given, otherwise the
+    /// native OS watcher
+    fn make_debouncer(&self, tx: crossbeam_channel::Sender<DebounceEventResult>) -> Result<AnyDebouncer> {
+        match self.poll_interval {
+            Some(interval) => {
+                let config = NotifyConfig::default().with_poll_interval(interval);
+                let debouncer =
+                    new_debouncer_opt::<_, PollWatcher>(Duration::from_millis(25), None, tx, config)?;
+                Ok(AnyDebouncer::Poll(debouncer))
+            }
+            None => {
+                let debouncer = new_debouncer(Duration::from_millis(25), None, tx)?;
+                Ok(AnyDebouncer::Native(debouncer))
+            }
+        }
+    }
+
+    /// Compiles a list of glob patterns (from `--filter`/`--ignore`) into a `GlobSet`
+    fn build_globset(patterns: &[String]) -> Result<GlobSet> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(Glob::new(pattern)?);
+        }
+        builder.build().map_err(Into::into)
+    }
+
     /// Creates an ignore matcher from ignore files in dir
     fn get_ignore_matcher(&self) -> Result<Gitignore> {
         let root = env::current_dir()?;
@@ -238,77 +769,22 @@ impl IoWatch {
         let matcher = builder.build()?;
         Ok(matcher)
     }
-
-    /// Get the sytem's shell command string
-    fn get_shell_cmd() -> Vec<String> {
-        if cfg!(windows) {
-            vec!["cmd".to_string(), "/c".to_string()]
-        } else {
-            // Assume GNU
-            let shell = env::var("SHELL").unwrap_or_else(|_| "/bin/sh".to_string());
-            vec![shell, "-c".to_string()]
-        }
-    }
-
-    /// Clear the terminal screen
-    fn clear_term_screen(&self) -> Result<()> {
-        Command::new("clear")
-            .status()
-            .or_else(|_| Command::new("cmd").args(&["/c", "cls"]).status())?;
-        Ok(())
-    }
-
-    /// Kill the utility if still running
-    fn kill_utility(&mut self) -> Result<()> {
-        match self.utility_process {
-            Some(ref mut child) => kill(child, &self.kill_sig),
-            None => Ok(()),
-        }
-    }
-
-    /// Wait for a delay in ms
-    fn wait_delay(&self) -> Result<()> {
-        thread::sleep(Duration::from_millis(self.delay));
-        Ok(())
-    }
-
-    /// Run the provided utility
-    fn run_utility(&mut self) -> Result<()> {
-        if self.utility_process.is_some() {
-            self.kill_utility()?;
-        }
-
-        if self.clear_term {
-            self.clear_term_screen()
-                .context("Failed to clear terminal screen")?;
-        }
-
-        // apply delay only on subsequent runs
-        if !self.first_run && self.delay > 0 {
-            self.wait_delay()?;
-        }
-
-        self.utility_process = Some(spawn(&self.utility_cmd[0], &self.utility_cmd[1..])?);
-
-        self.first_run = false;
-
-        Ok(())
-    }
 }
 
-impl TryFrom<Cli> for IoWatch {
+impl TryFrom<Cli> for IoWatch<CommandHandler> {
     type Error = anyhow::Error;
     fn try_from(cli: Cli) -> Result<Self> {
         let mut cli = cli;
+        let mut utility_args = std::mem::take(&mut cli.utility);
         let utility = if !cli.use_shell {
-            cli.utility
+            utility_args
         } else {
-            let mut shell = IoWatch::get_shell_cmd();
-            shell.append(&mut cli.utility);
+            let mut shell = CommandHandler::get_shell_cmd();
+            shell.append(&mut utility_args);
             shell
         };
 
-        let files: Vec<String> = if let Some(file) = cli.input_file {
+        let files: Vec<String> = if let Some(file) = cli.input_file.take() {
             debug!("Using input file: {}", file);
             vec![file]
         } else {
@@ -334,23 +810,28 @@ impl TryFrom<Cli> for IoWatch {
             RecursiveMode::NonRecursive
         };
 
+        let stop_timeout = Duration::from_millis(cli.stop_timeout);
+        let poll_interval = cli.poll.take().map(Duration::from_millis);
+        let filter_globs = std::mem::take(&mut cli.filter);
+        let ignore_globs = std::mem::take(&mut cli.ignore);
+        let handler = CommandHandler::from_cli(&cli, utility);
+
         Ok(IoWatch {
             exit_after: cli.exit_after,
             postpone: cli.postpone,
             recursive_mode: recursive,
-            first_run: true,
-            utility_cmd: utility,
             files,
-            delay: cli.delay,
-            clear_term: cli.clear_term,
             timeout: Duration::from_secs(cli.timeout.unwrap_or(u64::MAX)),
-            kill_sig: cli.kill_signal,
-            utility_process: None,
+            stop_timeout,
+            poll_interval,
+            filter_globs,
+            ignore_globs,
+            handler,
         })
     }
 }
 
-impl Debug for IoWatch {
+impl<H: ActionHandler + Debug> Debug for IoWatch<H> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("IoWatch")
             .field("exit_after", &self.exit_after)
@@ -358,10 +839,11 @@ impl Debug for IoWatch {
             .field("recursive_mode", &self.recursive_mode)
             .field("files", &self.files)
             .field("timeout", &self.timeout)
-            .field("delay", &self.delay)
-            .field("clear_term", &self.clear_term)
-            .field("kill_sig", &self.kill_sig)
-            .field("utility_cmd", &self.utility_cmd)
+            .field("stop_timeout", &self.stop_timeout)
+            .field("poll_interval", &self.poll_interval)
+            .field("filter_globs", &self.filter_globs)
+            .field("ignore_globs", &self.ignore_globs)
+            .field("handler", &self.handler)
             .finish()
     }
 }